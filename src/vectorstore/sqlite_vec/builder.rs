@@ -0,0 +1,94 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use sqlx::{Pool, Sqlite};
+
+use crate::embedding::embedder_trait::Embedder;
+
+use super::Store;
+
+pub struct StoreBuilder {
+    pool: Option<Pool<Sqlite>>,
+    connection_url: Option<String>,
+    table: String,
+    vector_dimensions: i32,
+    embedder: Option<Arc<dyn Embedder>>,
+    pre_delete_collection: bool,
+}
+
+impl StoreBuilder {
+    pub fn new() -> Self {
+        StoreBuilder {
+            pool: None,
+            connection_url: None,
+            table: "documents".to_string(),
+            vector_dimensions: 0,
+            embedder: None,
+            pre_delete_collection: false,
+        }
+    }
+
+    pub fn pool(mut self, pool: Pool<Sqlite>) -> Self {
+        self.pool = Some(pool);
+        self.connection_url = None;
+        self
+    }
+
+    pub fn connection_url(mut self, connection_url: &str) -> Self {
+        self.connection_url = Some(connection_url.to_string());
+        self.pool = None;
+        self
+    }
+
+    pub fn table(mut self, table: &str) -> Self {
+        self.table = table.to_string();
+        self
+    }
+
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Drop and recreate the collection's tables and triggers on
+    /// [`Store::initialize`]. Useful for re-index and test workflows that want
+    /// a clean collection. Defaults to `false`.
+    pub fn pre_delete_collection(mut self, pre_delete_collection: bool) -> Self {
+        self.pre_delete_collection = pre_delete_collection;
+        self
+    }
+
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let pool = match self.pool {
+            Some(pool) => pool,
+            None => {
+                let connection_url = self
+                    .connection_url
+                    .as_deref()
+                    .ok_or("either a pool or a connection_url is required")?;
+                Pool::<Sqlite>::connect(connection_url).await?
+            }
+        };
+
+        let embedder = self.embedder.ok_or("an embedder is required")?;
+
+        Ok(Store {
+            pool,
+            table: self.table,
+            vector_dimensions: self.vector_dimensions,
+            embedder,
+            pre_delete_collection: self.pre_delete_collection,
+        })
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,8 +1,11 @@
 use std::{collections::HashMap, error::Error, sync::Arc};
-use std::fmt::Display;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
 use async_trait::async_trait;
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use sqlx::{Pool, Row, Sqlite};
+use tokio::time::sleep;
 
 use crate::{
     embedding::embedder_trait::Embedder,
@@ -15,69 +18,282 @@ pub struct Store {
     pub(crate) table: String,
     pub(crate) vector_dimensions: i32,
     pub(crate) embedder: Arc<dyn Embedder>,
+    /// When set, [`Store::initialize`] drops and recreates the collection's
+    /// tables and triggers so re-index and test workflows start clean.
+    pub(crate) pre_delete_collection: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SqliteFilter {
-    Eq(String, String),
-    Cmp(std::cmp::Ordering, String, String),
-    In(String, Vec<String>),
+    /// `field == value`, comparing against the original JSON type of `value`.
+    Eq(String, Value),
+    /// Ordered comparison (`<`, `>`, `=`) against `value`'s JSON type.
+    Cmp(std::cmp::Ordering, String, Value),
+    /// `field IN (..)`, preserving each value's JSON type.
+    In(String, Vec<Value>),
+    /// Logical negation of the inner filter.
+    Not(Box<SqliteFilter>),
+    /// SQL `LIKE` match against the given pattern.
+    Like(String, String),
+    /// `field IS NULL` (absent or JSON-null metadata key).
+    IsNull(String),
     And(Vec<SqliteFilter>),
     Or(Vec<SqliteFilter>),
 }
 
-impl Display for SqliteFilter {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let str = match self {
-            SqliteFilter::Eq(a, b) => format!("json_extract(e.metadata, '$.{}') = {}", a.to_string(), b.to_string()),
-            SqliteFilter::Cmp(ordering, a, b) => {
+impl SqliteFilter {
+    /// Render the filter to a parameterized WHERE fragment plus the ordered
+    /// bind values it references. Values are never interpolated into the SQL
+    /// text — they are returned as `?` placeholders to be bound through sqlx,
+    /// which keeps filtering both injection-safe and correct for values that
+    /// contain quotes. Field names map to a `json_extract` over the metadata
+    /// column, with dotted paths becoming nested JSON paths (`a.b` -> `$.a.b`).
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut binds = Vec::new();
+        let clause = self.render(&mut binds);
+        (clause, binds)
+    }
+
+    fn render(&self, binds: &mut Vec<Value>) -> String {
+        match self {
+            SqliteFilter::Eq(field, value) => {
+                binds.push(value.clone());
+                format!("{} = ?", json_path(field))
+            }
+            SqliteFilter::Cmp(ordering, field, value) => {
                 let op = match ordering {
                     std::cmp::Ordering::Less => "<",
                     std::cmp::Ordering::Greater => ">",
                     std::cmp::Ordering::Equal => "=",
                 };
-                format!("json_extract(e.metadata, '$.{}') {} {}", a.to_string(), op, b.to_string())
+                binds.push(value.clone());
+                format!("{} {} ?", json_path(field), op)
             }
-            SqliteFilter::In(a, values) => {
-                format!(
-                    "json_extract(e.metadata, '$.{}') IN ({})",
-                    a.to_string(),
-                    values
-                        .iter()
-                        .map(|s| format!("'{}'", s))
-                        .collect::<Vec<String>>()
-                        .join(",")
-                )
+            SqliteFilter::In(field, values) => {
+                let placeholders = values
+                    .iter()
+                    .map(|value| {
+                        binds.push(value.clone());
+                        "?"
+                    })
+                    .collect::<Vec<&str>>()
+                    .join(",");
+                format!("{} IN ({})", json_path(field), placeholders)
             }
-            SqliteFilter::And(filters) => filters
-                .iter()
-                .map(|filter| filter.to_string())
-                .collect::<Vec<String>>()
-                .join(" AND "),
-            SqliteFilter::Or(filters) => filters
-                .iter()
-                .map(|filter| filter.to_string())
-                .collect::<Vec<String>>()
-                .join(" OR "),
+            SqliteFilter::Not(inner) => {
+                format!("NOT ({})", inner.render(binds))
+            }
+            SqliteFilter::Like(field, pattern) => {
+                binds.push(Value::String(pattern.clone()));
+                format!("{} LIKE ?", json_path(field))
+            }
+            SqliteFilter::IsNull(field) => {
+                format!("{} IS NULL", json_path(field))
+            }
+            SqliteFilter::And(filters) => join_filters(filters, "AND", binds),
+            SqliteFilter::Or(filters) => join_filters(filters, "OR", binds),
+        }
+    }
+}
+
+/// Render `field` (optionally a dotted path like `a.b.c`) as a `json_extract`
+/// over the metadata column using the matching JSON path `$.a.b.c`.
+fn json_path(field: &str) -> String {
+    format!("json_extract(e.metadata, '$.{field}')")
+}
+
+/// Escape an arbitrary user string into a single FTS5 phrase so that
+/// punctuation and operator keywords are treated as literal text rather than
+/// query syntax. Embedded double quotes are doubled per FTS5 phrase rules.
+fn fts5_phrase(query: &str) -> String {
+    format!("\"{}\"", query.replace('"', "\"\""))
+}
+
+/// Fuse several ranked lists of rowids with Reciprocal Rank Fusion. A rowid at
+/// 1-based rank `r` in a list contributes `weight / (k + r)`, summed across
+/// lists. Returns `(rowid, score)` pairs sorted by descending score.
+fn rrf_fuse(lists: &[(Vec<i64>, f64)], k: f64) -> Vec<(i64, f64)> {
+    let mut scores: HashMap<i64, f64> = HashMap::new();
+    for (ids, weight) in lists {
+        for (rank, rowid) in ids.iter().enumerate() {
+            let contribution = weight / (k + (rank as f64 + 1.0));
+            *scores.entry(*rowid).or_insert(0.0) += contribution;
+        }
+    }
+
+    let mut fused: Vec<(i64, f64)> = scores.into_iter().collect();
+    fused.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    fused
+}
+
+/// Parse candidate rows selected as `rowid`, `text`, `metadata` into
+/// `(rowid, Document)` pairs, preserving the query's ordering.
+fn candidates_from_rows(
+    rows: Vec<sqlx::sqlite::SqliteRow>,
+) -> Result<Vec<(i64, Document)>, sqlx::Error> {
+    rows.into_iter()
+        .map(|row| {
+            let rowid: i64 = row.try_get("rowid")?;
+            let page_content: String = row.try_get("text")?;
+            let metadata_json: Value = row.try_get("metadata")?;
+            let metadata = if let Value::Object(obj) = metadata_json {
+                obj.into_iter().collect()
+            } else {
+                HashMap::new()
+            };
+            Ok((
+                rowid,
+                Document {
+                    page_content,
+                    metadata,
+                    score: 0.0,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Render a list of sub-filters joined by `op`, each parenthesized so the
+/// result composes correctly when nested under `Not`/`And`/`Or`. An empty
+/// list renders as the identity for the operator.
+fn join_filters(filters: &[SqliteFilter], op: &str, binds: &mut Vec<Value>) -> String {
+    if filters.is_empty() {
+        return if op == "AND" { "TRUE" } else { "FALSE" }.to_string();
+    }
+    filters
+        .iter()
+        .map(|filter| format!("({})", filter.render(binds)))
+        .collect::<Vec<String>>()
+        .join(&format!(" {op} "))
+}
+
+/// Bind an ordered list of filter values onto a query, mapping each JSON value
+/// onto the corresponding SQLite scalar type so numeric metadata compares as a
+/// number and string metadata as text.
+fn bind_values<'q>(
+    mut query: sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    values: Vec<Value>,
+) -> sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+    for value in values {
+        query = match value {
+            Value::String(s) => query.bind(s),
+            Value::Bool(b) => query.bind(b),
+            Value::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    query.bind(i)
+                } else {
+                    query.bind(n.as_f64().unwrap_or_default())
+                }
+            }
+            Value::Null => query.bind(Option::<String>::None),
+            other => query.bind(other.to_string()),
         };
-        write!(f, "{}", str)
     }
+    query
 }
 
-pub type SqliteOptions = VecStoreOptions<SqliteFilter>;
+/// Options for the sqlite-vec store.
+///
+/// Wraps the shared [`VecStoreOptions`] (name space, score threshold, filters,
+/// per-call embedder override) and adds settings that are specific to this
+/// backend. It derefs to the inner [`VecStoreOptions`] so the usual builder
+/// methods and fields keep working unchanged.
+#[derive(Clone)]
+pub struct SqliteOptions {
+    pub base: VecStoreOptions<SqliteFilter>,
+    /// Weight given to the dense (vector) result list when fusing hybrid
+    /// search results, in `0.0..=1.0`. The keyword list is weighted by
+    /// `1.0 - semantic_ratio`, so `1.0` is pure semantic and `0.0` is pure
+    /// lexical. Defaults to `0.5`.
+    pub semantic_ratio: f32,
+    /// Size of the candidate pool fetched from each retriever before RRF
+    /// fusion in [`Store::hybrid_search`]. Should be comfortably larger than
+    /// the requested `limit` so fusion can reorder across both lists; clamped
+    /// up to `limit` at call time. Defaults to `50`.
+    pub hybrid_fetch_k: usize,
+    /// Maximum number of documents sent to the embedder in a single batch.
+    /// Defaults to `100`.
+    pub embed_batch_size: usize,
+    /// Approximate token budget per embedding batch; a batch is flushed as
+    /// soon as either this or [`Self::embed_batch_size`] would be exceeded.
+    /// Defaults to `8192`.
+    pub embed_token_budget: usize,
+    /// Maximum number of times a batch is retried after a transient /
+    /// rate-limit error from the embedder. Defaults to `5`.
+    pub embed_max_retries: u32,
+    /// Base delay for the exponential backoff applied between embedding
+    /// retries (`base * 2^attempt`). Defaults to 500ms.
+    pub embed_base_delay: Duration,
+    /// Identity of the embedding model, mixed into the embedding-cache key so a
+    /// cache hit can never serve a vector produced by a different model. Set
+    /// this to something that uniquely names the model (and change it whenever
+    /// the model does, e.g. via `opt.embedder`); the vector dimension is always
+    /// folded in as well.
+    ///
+    /// Defaults to empty, which **disables** the cache: with no model identity
+    /// a hit could cross models, so `add_documents` embeds everything fresh
+    /// until a namespace is set. Caching is therefore opt-in and never the
+    /// unsafe default.
+    pub embed_cache_namespace: String,
+}
 
 impl Default for SqliteOptions {
     fn default() -> Self {
-        Self::new()
+        Self {
+            base: VecStoreOptions::new(),
+            semantic_ratio: 0.5,
+            hybrid_fetch_k: 50,
+            embed_batch_size: 100,
+            embed_token_budget: 8192,
+            embed_max_retries: 5,
+            embed_base_delay: Duration::from_millis(500),
+            embed_cache_namespace: String::new(),
+        }
+    }
+}
+
+impl Deref for SqliteOptions {
+    type Target = VecStoreOptions<SqliteFilter>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for SqliteOptions {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
     }
 }
 
 impl Store {
     pub async fn initialize(&self) -> Result<(), Box<dyn Error>> {
+        if self.pre_delete_collection {
+            self.drop_collection().await?;
+        }
         self.create_table_if_not_exists().await?;
         Ok(())
     }
 
+    async fn drop_collection(&self) -> Result<(), Box<dyn Error>> {
+        let table = &self.table;
+
+        // Drop in dependency order: triggers first, then the shadow tables, and
+        // finally the main table they hang off of.
+        for stmt in [
+            format!("DROP TRIGGER IF EXISTS embed_text_{table}"),
+            format!("DROP TRIGGER IF EXISTS fts_text_{table}"),
+            format!("DROP TABLE IF EXISTS fts_{table}"),
+            format!("DROP TABLE IF EXISTS vec_{table}"),
+            format!("DROP TABLE IF EXISTS embed_cache_{table}"),
+            format!("DROP TABLE IF EXISTS {table}"),
+        ] {
+            sqlx::query(&stmt).execute(&self.pool).await?;
+        }
+
+        Ok(())
+    }
+
     async fn create_table_if_not_exists(&self) -> Result<(), Box<dyn Error>> {
         let table = &self.table;
 
@@ -122,46 +338,338 @@ impl Store {
         .execute(&self.pool)
         .await?;
 
+        // Persistent embedding cache keyed by a hash of the model namespace,
+        // vector dimension and source text, so re-ingesting identical content
+        // under the same model skips re-embedding. It is only read and written
+        // when `embed_cache_namespace` is set.
+        sqlx::query(&format!(
+            r#"
+                CREATE TABLE IF NOT EXISTS embed_cache_{table}
+                (
+                  hash TEXT PRIMARY KEY,
+                  embedding BLOB
+                )
+                ;
+                "#
+        ))
+        .execute(&self.pool)
+        .await?;
+
+        // Keyword side of hybrid search: an FTS5 index over the `text` column,
+        // kept in sync by the trigger below (the external-content option lets
+        // the index share storage with the main table).
+        sqlx::query(&format!(
+            r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS fts_{table} USING fts5(
+                  text,
+                  content='{table}',
+                  content_rowid='rowid'
+                );
+                "#
+        ))
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(&format!(
+            r#"
+                CREATE TRIGGER IF NOT EXISTS fts_text_{table}
+                AFTER INSERT ON {table}
+                BEGIN
+                    INSERT INTO fts_{table}(rowid, text)
+                    VALUES (new.rowid, new.text)
+                    ;
+                END;
+                "#
+        ))
+        .execute(&self.pool)
+        .await?;
+
         Ok(())
     }
 
-    fn get_filters(&self, opt: &SqliteOptions) -> Result<String, Box<dyn Error>> {
+    fn get_filters(&self, opt: &SqliteOptions) -> (String, Vec<Value>) {
         match &opt.filters {
-            Some(filter) => Ok(filter.to_string()),
-            None => Ok("TRUE".to_string()),
+            Some(filter) => filter.to_sql(),
+            None => ("TRUE".to_string(), Vec::new()),
         }
     }
-}
 
-#[async_trait]
-impl VectorStore for Store {
-    type Options = SqliteOptions;
+    /// Hybrid dense + keyword retrieval.
+    ///
+    /// Runs the vec0 KNN query and an FTS5 `MATCH` / `bm25` query independently
+    /// and fuses them with Reciprocal Rank Fusion: a document at 1-based rank
+    /// `r` in a list contributes `1/(k + r)` (with `k = 60`) to its score. The
+    /// vector list is scaled by `opt.semantic_ratio` and the keyword list by
+    /// `1.0 - opt.semantic_ratio` before the two contributions are summed, so
+    /// callers can bias toward lexical or semantic matches. The top `limit`
+    /// documents by fused score are returned.
+    pub async fn hybrid_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &SqliteOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        const RRF_K: f64 = 60.0;
 
-    async fn add_documents(
+        let table = &self.table;
+        let (filter, filter_binds) = self.get_filters(opt);
+
+        // Pull a candidate pool wider than `limit` from each retriever so that
+        // fusion can promote a document that sits just outside either list's
+        // top-`limit`; we only trim to `limit` after fusing.
+        let fetch_k = opt.hybrid_fetch_k.max(limit);
+
+        let query_vector = json!(self.embedder.embed_query(query).await?);
+
+        let vec_sql = format!(
+            r#"SELECT
+                    e.rowid AS rowid,
+                    e.text AS text,
+                    e.metadata AS metadata
+                FROM {table} e
+                INNER JOIN vec_{table} v on v.rowid = e.rowid
+                WHERE v.text_embedding match '{query_vector}' AND k = ? AND {filter}
+                ORDER BY distance
+                LIMIT ?"#
+        );
+        let mut vec_query = sqlx::query(&vec_sql).bind(fetch_k as i32);
+        vec_query = bind_values(vec_query, filter_binds.clone());
+        let vec_candidates = candidates_from_rows(
+            vec_query.bind(fetch_k as i32).fetch_all(&self.pool).await?,
+        )?;
+
+        // FTS5 parses the MATCH string as query syntax, so natural-language
+        // input with significant punctuation (or an empty string) would raise a
+        // syntax error. Bind it as a single escaped phrase, and skip the keyword
+        // leg entirely when the query is blank.
+        let fts_candidates = if query.trim().is_empty() {
+            Vec::new()
+        } else {
+            let fts_sql = format!(
+                r#"SELECT
+                        e.rowid AS rowid,
+                        e.text AS text,
+                        e.metadata AS metadata
+                    FROM fts_{table} f
+                    INNER JOIN {table} e on e.rowid = f.rowid
+                    WHERE fts_{table} match ? AND {filter}
+                    ORDER BY bm25(fts_{table})
+                    LIMIT ?"#
+            );
+            let mut fts_query = sqlx::query(&fts_sql).bind(fts5_phrase(query));
+            fts_query = bind_values(fts_query, filter_binds);
+            candidates_from_rows(fts_query.bind(fetch_k as i32).fetch_all(&self.pool).await?)?
+        };
+
+        let semantic_ratio = opt.semantic_ratio as f64;
+
+        // Keep the first rendering we see of each document, then fuse on rowid.
+        let mut docs_by_id: HashMap<i64, Document> = HashMap::new();
+        let vec_ids: Vec<i64> = vec_candidates
+            .iter()
+            .map(|(rowid, _)| *rowid)
+            .collect();
+        let fts_ids: Vec<i64> = fts_candidates
+            .iter()
+            .map(|(rowid, _)| *rowid)
+            .collect();
+        for (rowid, doc) in vec_candidates.into_iter().chain(fts_candidates) {
+            docs_by_id.entry(rowid).or_insert(doc);
+        }
+
+        let fused = rrf_fuse(
+            &[(vec_ids, semantic_ratio), (fts_ids, 1.0 - semantic_ratio)],
+            RRF_K,
+        );
+
+        let docs = fused
+            .into_iter()
+            .take(limit)
+            .filter_map(|(rowid, score)| {
+                docs_by_id.remove(&rowid).map(|mut doc| {
+                    doc.score = score;
+                    doc
+                })
+            })
+            .collect();
+
+        Ok(docs)
+    }
+
+    /// "More like this": find the nearest neighbors of an already-indexed
+    /// document by its `rowid`, reusing the embedding stored at insert time so
+    /// no embedder round-trip is needed. The seed document is excluded from the
+    /// results.
+    pub async fn similarity_search_by_id(
         &self,
-        docs: &[Document],
-        opt: &Self::Options,
+        rowid: i64,
+        limit: usize,
+        opt: &SqliteOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let table = &self.table;
+
+        let row = sqlx::query(&format!(
+            r#"SELECT text_embedding FROM {table} WHERE rowid = ?"#
+        ))
+        .bind(rowid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let text_embedding: String = row.try_get("text_embedding")?;
+        let vector: Vec<f64> = serde_json::from_str(&text_embedding)?;
+
+        self.search_by_vector(&vector, limit, opt, Some(rowid)).await
+    }
+
+    /// Find the nearest neighbors of a raw query vector, without re-embedding a
+    /// query string. Useful for recommendation-style flows that already have an
+    /// embedding in hand.
+    pub async fn similarity_search_by_vector(
+        &self,
+        vector: &[f64],
+        limit: usize,
+        opt: &SqliteOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        self.search_by_vector(vector, limit, opt, None).await
+    }
+
+    async fn search_by_vector(
+        &self,
+        vector: &[f64],
+        limit: usize,
+        opt: &SqliteOptions,
+        exclude: Option<i64>,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let table = &self.table;
+        let (filter, filter_binds) = self.get_filters(opt);
+        let query_vector = json!(vector);
+
+        // `k` is widened by one when a seed is excluded so that removing it does
+        // not leave us one neighbor short of `limit`.
+        let k = exclude.map_or(limit, |_| limit + 1) as i32;
+        let exclusion = match exclude {
+            Some(seed) => format!("e.rowid != {seed}"),
+            None => "TRUE".to_string(),
+        };
+
+        let sql = format!(
+            r#"SELECT
+                    text,
+                    metadata,
+                    distance
+                FROM {table} e
+                INNER JOIN vec_{table} v on v.rowid = e.rowid
+                WHERE v.text_embedding match '{query_vector}' AND k = ? AND {exclusion} AND {filter}
+                ORDER BY distance
+                LIMIT ?"#
+        );
+        let mut query = sqlx::query(&sql).bind(k);
+        query = bind_values(query, filter_binds);
+        let rows = query.bind(limit as i32).fetch_all(&self.pool).await?;
+
+        let docs = rows
+            .into_iter()
+            .map(|row| {
+                let page_content: String = row.try_get("text")?;
+                let metadata_json: Value = row.try_get("metadata")?;
+                let score: f64 = row.try_get("distance")?;
+
+                let metadata = if let Value::Object(obj) = metadata_json {
+                    obj.into_iter().collect()
+                } else {
+                    HashMap::new()
+                };
+
+                Ok(Document {
+                    page_content,
+                    metadata,
+                    score,
+                })
+            })
+            .collect::<Result<Vec<Document>, sqlx::Error>>()?;
+
+        Ok(docs)
+    }
+
+    /// Embed the cache misses in `batch`, then write the batch's cache entries
+    /// and main-table rows in a single transaction. Returns the inserted
+    /// rowids as strings, in batch order.
+    async fn flush_batch(
+        &self,
+        batch: &[&Document],
+        embedder: &dyn Embedder,
+        opt: &SqliteOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let table = &self.table;
+
+        // The cache is only consulted when a model identity is configured: an
+        // entry keyed on an ambiguous identity could serve a vector produced by
+        // a different model of the same dimension (trivially reachable via the
+        // per-call `opt.embedder` override), silently corrupting retrieval. With
+        // no namespace we therefore embed everything fresh rather than risk a
+        // cross-model hit.
+        let hashes: Vec<String> = batch
+            .iter()
+            .map(|d| {
+                embed_cache_key(
+                    &opt.embed_cache_namespace,
+                    self.vector_dimensions,
+                    &d.page_content,
+                )
+            })
+            .collect();
+
+        let cache_enabled = !opt.embed_cache_namespace.is_empty();
 
-        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let mut embeddings: Vec<Option<Vec<f64>>> = vec![None; batch.len()];
+        if cache_enabled {
+            for (i, hash) in hashes.iter().enumerate() {
+                let cached = sqlx::query(&format!(
+                    r#"SELECT embedding FROM embed_cache_{table} WHERE hash = ?"#
+                ))
+                .bind(hash)
+                .fetch_optional(&self.pool)
+                .await?;
 
-        let vectors = embedder.embed_documents(&texts).await?;
-        if vectors.len() != docs.len() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Number of vectors and documents do not match",
-            )));
+                if let Some(row) = cached {
+                    let blob: String = row.try_get("embedding")?;
+                    embeddings[i] = Some(serde_json::from_str(&blob)?);
+                }
+            }
         }
 
-        let table = &self.table;
+        let miss_indices: Vec<usize> = (0..batch.len())
+            .filter(|i| embeddings[*i].is_none())
+            .collect();
+
+        if !miss_indices.is_empty() {
+            let miss_texts: Vec<String> = miss_indices
+                .iter()
+                .map(|&i| batch[i].page_content.clone())
+                .collect();
+
+            let vectors = self.embed_with_backoff(embedder, &miss_texts, opt).await?;
+            if vectors.len() != miss_texts.len() {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "Number of vectors and documents do not match",
+                )));
+            }
+
+            for (j, &i) in miss_indices.iter().enumerate() {
+                embeddings[i] = Some(vectors[j].clone());
+            }
+        }
 
         let mut tx = self.pool.begin().await?;
+        let mut ids = Vec::with_capacity(batch.len());
 
-        let mut ids = Vec::with_capacity(docs.len());
+        for (i, doc) in batch.iter().enumerate() {
+            let vector = embeddings[i]
+                .as_ref()
+                .expect("every batch entry has an embedding by now");
+            let text_embedding = json!(vector).to_string();
 
-        for (doc, vector) in docs.iter().zip(vectors.iter()) {
-            let text_embedding = json!(&vector);
             let id = sqlx::query(&format!(
                 r#"
                     INSERT INTO {table}
@@ -171,7 +679,7 @@ impl VectorStore for Store {
             ))
             .bind(&doc.page_content)
             .bind(json!(&doc.metadata))
-            .bind(text_embedding.to_string())
+            .bind(text_embedding)
             .execute(&mut *tx)
             .await?
             .last_insert_rowid();
@@ -179,8 +687,203 @@ impl VectorStore for Store {
             ids.push(id.to_string());
         }
 
+        if cache_enabled {
+            for &i in &miss_indices {
+                let embedding = json!(embeddings[i].as_ref().unwrap()).to_string();
+                sqlx::query(&format!(
+                    r#"INSERT OR IGNORE INTO embed_cache_{table} (hash, embedding) VALUES (?,?)"#
+                ))
+                .bind(&hashes[i])
+                .bind(embedding)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(ids)
+    }
+
+    /// Call `embed_documents`, retrying with exponential backoff when the
+    /// embedder reports a transient / rate-limit error. Non-transient errors
+    /// and exhausted retries propagate to the caller.
+    async fn embed_with_backoff(
+        &self,
+        embedder: &dyn Embedder,
+        texts: &[String],
+        opt: &SqliteOptions,
+    ) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+        let mut attempt = 0u32;
+        loop {
+            match embedder.embed_documents(texts).await {
+                Ok(vectors) => return Ok(vectors),
+                Err(err) => {
+                    if attempt >= opt.embed_max_retries || !is_transient_error(err.as_ref()) {
+                        return Err(err);
+                    }
+                    sleep(opt.embed_base_delay * 2u32.pow(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Delete documents by rowid (as returned from [`VectorStore::add_documents`])
+    /// from the main table and its shadow tables in a single transaction.
+    pub async fn delete_documents(
+        &self,
+        ids: &[String],
+        _opt: &SqliteOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let rowids = ids
+            .iter()
+            .map(|id| id.parse::<i64>())
+            .collect::<Result<Vec<i64>, _>>()?;
+        self.delete_rowids(&rowids).await
+    }
+
+    /// Delete every document whose metadata matches `filter`, keeping the main
+    /// table and its shadow tables consistent. Reuses [`SqliteFilter::to_sql`]
+    /// to select the rows to remove.
+    pub async fn delete_by_filter(
+        &self,
+        filter: &SqliteFilter,
+    ) -> Result<(), Box<dyn Error>> {
+        let table = &self.table;
+        let (clause, binds) = filter.to_sql();
+
+        let sql = format!(r#"SELECT e.rowid AS rowid FROM {table} e WHERE {clause}"#);
+        let rows = bind_values(sqlx::query(&sql), binds)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let rowids = rows
+            .into_iter()
+            .map(|row| row.try_get("rowid"))
+            .collect::<Result<Vec<i64>, sqlx::Error>>()?;
+
+        self.delete_rowids(&rowids).await
+    }
+
+    async fn delete_rowids(&self, rowids: &[i64]) -> Result<(), Box<dyn Error>> {
+        if rowids.is_empty() {
+            return Ok(());
+        }
+
+        let table = &self.table;
+        let mut tx = self.pool.begin().await?;
+
+        // There are no AFTER DELETE triggers on the main table, so each shadow
+        // table is cleaned up explicitly to avoid orphaned embeddings. The FTS5
+        // index is external-content, so it is updated with the special
+        // `'delete'` command (which needs the stored text) before the source
+        // row goes away.
+        for rowid in rowids {
+            sqlx::query(&format!(
+                r#"INSERT INTO fts_{table}(fts_{table}, rowid, text)
+                   SELECT 'delete', rowid, text FROM {table} WHERE rowid = ?"#
+            ))
+            .bind(rowid)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(&format!(r#"DELETE FROM vec_{table} WHERE rowid = ?"#))
+                .bind(rowid)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(&format!(r#"DELETE FROM {table} WHERE rowid = ?"#))
+                .bind(rowid)
+                .execute(&mut *tx)
+                .await?;
+        }
+
         tx.commit().await?;
 
+        Ok(())
+    }
+}
+
+/// Embedding-cache key: a SHA-256 hex digest over the model namespace, the
+/// vector dimension and the source text. Folding the model identity in means a
+/// cache hit can never cross models as long as each model has its own
+/// namespace (the embedder itself is not part of the key), while identical
+/// text under the same model still skips re-embedding. The
+/// fields are length-prefixed so no two distinct inputs can collide by
+/// concatenation.
+fn embed_cache_key(namespace: &str, dimensions: i32, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update((namespace.len() as u64).to_le_bytes());
+    hasher.update(namespace.as_bytes());
+    hasher.update(dimensions.to_le_bytes());
+    hasher.update((text.len() as u64).to_le_bytes());
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Rough token estimate (~4 characters per token) used only to decide when to
+/// flush an embedding batch; it never needs to be exact.
+fn approx_tokens(text: &str) -> usize {
+    text.chars().count() / 4 + 1
+}
+
+/// Heuristic for whether an embedder error is worth retrying. Embedders surface
+/// provider errors as opaque `Box<dyn Error>`, so we match on the rendered
+/// message for the usual rate-limit / transient signals.
+fn is_transient_error(err: &dyn Error) -> bool {
+    let message = err.to_string().to_lowercase();
+    [
+        "rate limit",
+        "ratelimit",
+        "429",
+        "timeout",
+        "timed out",
+        "temporarily",
+        "try again",
+        "transient",
+        "overloaded",
+        "503",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    type Options = SqliteOptions;
+
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &Self::Options,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder).as_ref();
+
+        let mut ids = Vec::with_capacity(docs.len());
+
+        // Group documents into batches bounded by both an item count and an
+        // approximate token budget, flushing whenever either limit would be
+        // exceeded. Each flushed batch embeds only its cache misses and writes
+        // the batch atomically, so a large ingest makes steady progress instead
+        // of succeeding or failing as a single giant transaction.
+        let mut batch: Vec<&Document> = Vec::new();
+        let mut batch_tokens = 0usize;
+
+        for doc in docs {
+            let tokens = approx_tokens(&doc.page_content);
+            let over_budget = batch_tokens + tokens > opt.embed_token_budget;
+            if !batch.is_empty() && (batch.len() >= opt.embed_batch_size || over_budget) {
+                ids.extend(self.flush_batch(&batch, embedder, opt).await?);
+                batch.clear();
+                batch_tokens = 0;
+            }
+            batch.push(doc);
+            batch_tokens += tokens;
+        }
+
+        if !batch.is_empty() {
+            ids.extend(self.flush_batch(&batch, embedder, opt).await?);
+        }
+
         Ok(ids)
     }
 
@@ -193,9 +896,9 @@ impl VectorStore for Store {
         let table = &self.table;
 
         let query_vector = json!(self.embedder.embed_query(query).await?);
-        let filter = self.get_filters(opt)?;
+        let (filter, filter_binds) = self.get_filters(opt);
 
-        let rows = sqlx::query(&format!(
+        let sql = format!(
             r#"SELECT
                     text,
                     metadata,
@@ -205,11 +908,10 @@ impl VectorStore for Store {
                 WHERE v.text_embedding match '{query_vector}' AND k = ? AND {filter}
                 ORDER BY distance
                 LIMIT ?"#
-        ))
-        .bind(limit as i32)
-        .bind(limit as i32)
-        .fetch_all(&self.pool)
-        .await?;
+        );
+        let mut query = sqlx::query(&sql).bind(limit as i32);
+        query = bind_values(query, filter_binds);
+        let rows = query.bind(limit as i32).fetch_all(&self.pool).await?;
 
         let docs = rows
             .into_iter()
@@ -235,3 +937,358 @@ impl VectorStore for Store {
         Ok(docs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fts5_phrase_wraps_and_escapes() {
+        assert_eq!(fts5_phrase("hello world"), "\"hello world\"");
+        // operator keywords and punctuation become literal phrase text
+        assert_eq!(fts5_phrase("fast AND cheap"), "\"fast AND cheap\"");
+        assert_eq!(fts5_phrase("a-b c*"), "\"a-b c*\"");
+        // embedded quotes are doubled, not left to break out of the phrase
+        assert_eq!(fts5_phrase(r#"say "hi""#), r#""say ""hi""""#);
+    }
+
+    #[test]
+    fn rrf_fuse_sums_weighted_contributions() {
+        // doc 1 is rank 1 in both lists, doc 2 rank 2 in both.
+        let fused = rrf_fuse(&[(vec![1, 2], 1.0), (vec![1, 2], 1.0)], 60.0);
+        assert_eq!(fused[0].0, 1);
+        assert_eq!(fused[1].0, 2);
+        assert!((fused[0].1 - 2.0 / 61.0).abs() < 1e-9);
+        assert!((fused[1].1 - 2.0 / 62.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rrf_fuse_promotes_consensus_over_single_list_leader() {
+        // doc 9 tops only the vector list; doc 5 is second in both and wins on
+        // fused score, which is the whole point of fusing wider pools.
+        let fused = rrf_fuse(&[(vec![9, 5], 1.0), (vec![7, 5], 1.0)], 60.0);
+        assert_eq!(fused[0].0, 5);
+    }
+
+    #[test]
+    fn rrf_fuse_weighting_biases_toward_the_heavier_list() {
+        // pure-semantic weighting: only the vector list counts.
+        let fused = rrf_fuse(&[(vec![1], 1.0), (vec![2], 0.0)], 60.0);
+        assert_eq!(fused[0].0, 1);
+        assert!((fused.iter().find(|(id, _)| *id == 2).unwrap().1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn embed_cache_key_is_stable_for_same_model() {
+        assert_eq!(
+            embed_cache_key("text-embedding-3-small", 1536, "hello"),
+            embed_cache_key("text-embedding-3-small", 1536, "hello"),
+        );
+    }
+
+    #[test]
+    fn embed_cache_key_separates_models_and_dimensions() {
+        let text = "hello";
+        let base = embed_cache_key("model-a", 1536, text);
+        // same dimension, different model must not collide
+        assert_ne!(base, embed_cache_key("model-b", 1536, text));
+        // same model, different dimension must not collide
+        assert_ne!(base, embed_cache_key("model-a", 768, text));
+        // length prefixing prevents namespace/text boundary collisions
+        assert_ne!(
+            embed_cache_key("ab", 1536, "cd"),
+            embed_cache_key("abc", 1536, "d"),
+        );
+    }
+
+    #[test]
+    fn is_transient_error_matches_rate_limit_signals() {
+        fn err(msg: &str) -> Box<dyn Error> {
+            Box::new(std::io::Error::other(msg.to_string()))
+        }
+        assert!(is_transient_error(err("HTTP 429 Too Many Requests").as_ref()));
+        assert!(is_transient_error(err("Rate limit reached").as_ref()));
+        assert!(is_transient_error(err("service Overloaded").as_ref()));
+        assert!(is_transient_error(err("request timed out").as_ref()));
+        assert!(!is_transient_error(err("invalid api key").as_ref()));
+        assert!(!is_transient_error(err("400 bad request").as_ref()));
+    }
+
+    #[test]
+    fn to_sql_eq_is_parameterized_and_typed() {
+        let (clause, binds) = SqliteFilter::Eq("author".into(), json!("o'brien")).to_sql();
+        assert_eq!(clause, "json_extract(e.metadata, '$.author') = ?");
+        // the value is a bind, never interpolated, so quotes are harmless
+        assert_eq!(binds, vec![json!("o'brien")]);
+    }
+
+    #[test]
+    fn to_sql_cmp_preserves_numeric_type() {
+        let (clause, binds) =
+            SqliteFilter::Cmp(std::cmp::Ordering::Greater, "views".into(), json!(100)).to_sql();
+        assert_eq!(clause, "json_extract(e.metadata, '$.views') > ?");
+        assert_eq!(binds, vec![json!(100)]);
+        assert!(binds[0].is_number());
+    }
+
+    #[test]
+    fn to_sql_in_emits_one_placeholder_per_value() {
+        let (clause, binds) =
+            SqliteFilter::In("tag".into(), vec![json!("a"), json!("b"), json!("c")]).to_sql();
+        assert_eq!(clause, "json_extract(e.metadata, '$.tag') IN (?,?,?)");
+        assert_eq!(binds, vec![json!("a"), json!("b"), json!("c")]);
+    }
+
+    #[test]
+    fn to_sql_supports_nested_paths_like_is_null_and_not() {
+        let (clause, binds) = SqliteFilter::Like("a.b.c".into(), "%x%".into()).to_sql();
+        assert_eq!(clause, "json_extract(e.metadata, '$.a.b.c') LIKE ?");
+        assert_eq!(binds, vec![json!("%x%")]);
+
+        let (clause, binds) = SqliteFilter::IsNull("deleted".into()).to_sql();
+        assert_eq!(clause, "json_extract(e.metadata, '$.deleted') IS NULL");
+        assert!(binds.is_empty());
+
+        let (clause, binds) =
+            SqliteFilter::Not(Box::new(SqliteFilter::Eq("k".into(), json!(1)))).to_sql();
+        assert_eq!(clause, "NOT (json_extract(e.metadata, '$.k') = ?)");
+        assert_eq!(binds, vec![json!(1)]);
+    }
+
+    #[test]
+    fn to_sql_nests_and_or_with_ordered_binds() {
+        let filter = SqliteFilter::And(vec![
+            SqliteFilter::Eq("lang".into(), json!("rust")),
+            SqliteFilter::Or(vec![
+                SqliteFilter::Cmp(std::cmp::Ordering::Greater, "stars".into(), json!(10)),
+                SqliteFilter::IsNull("archived".into()),
+            ]),
+        ]);
+        let (clause, binds) = filter.to_sql();
+        assert_eq!(
+            clause,
+            "(json_extract(e.metadata, '$.lang') = ?) AND \
+             ((json_extract(e.metadata, '$.stars') > ?) OR \
+             (json_extract(e.metadata, '$.archived') IS NULL))"
+        );
+        // binds are emitted left-to-right in the order their placeholders appear
+        assert_eq!(binds, vec![json!("rust"), json!(10)]);
+    }
+
+    #[test]
+    fn to_sql_empty_and_or_render_identity() {
+        assert_eq!(SqliteFilter::And(vec![]).to_sql().0, "TRUE");
+        assert_eq!(SqliteFilter::Or(vec![]).to_sql().0, "FALSE");
+    }
+}
+
+#[cfg(test)]
+mod db_tests {
+    use super::*;
+
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use crate::schemas::Document;
+    use crate::vectorstore::sqlite_vec::builder::StoreBuilder;
+
+    const DIM: i32 = 4;
+
+    /// Deterministic embedder so tests can assert on retrieval without a real
+    /// model: each text maps to a fixed vector derived from its bytes.
+    struct MockEmbedder;
+
+    #[async_trait]
+    impl Embedder for MockEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+            Ok(documents.iter().map(|d| embed(d)).collect())
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+            Ok(embed(text))
+        }
+    }
+
+    fn embed(text: &str) -> Vec<f64> {
+        let mut v = vec![0.0; DIM as usize];
+        for (i, byte) in text.bytes().enumerate() {
+            v[i % DIM as usize] += byte as f64;
+        }
+        v
+    }
+
+    fn doc(text: &str, metadata: &[(&str, Value)]) -> Document {
+        Document {
+            page_content: text.to_string(),
+            metadata: metadata
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            score: 0.0,
+        }
+    }
+
+    /// Build an initialized store over a single shared in-memory connection.
+    async fn memory_store() -> Store {
+        // A single connection keeps every query on the same in-memory database.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = StoreBuilder::new()
+            .pool(pool)
+            .table("documents")
+            .vector_dimensions(DIM)
+            .embedder(MockEmbedder)
+            .build()
+            .await
+            .unwrap();
+        store.initialize().await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn delete_documents_removes_from_all_tables() {
+        let store = memory_store().await;
+        let opt = SqliteOptions::default();
+
+        let ids = store
+            .add_documents(&[doc("alpha", &[]), doc("beta", &[]), doc("gamma", &[])], &opt)
+            .await
+            .unwrap();
+
+        store.delete_documents(&ids[..1], &opt).await.unwrap();
+
+        // The deleted row must not resurface through the vec0 join...
+        let hits = store.similarity_search("alpha", 10, &opt).await.unwrap();
+        assert!(hits.iter().all(|d| d.page_content != "alpha"));
+        assert_eq!(hits.len(), 2);
+
+        // ...nor through the FTS index, whose external-content 'delete' sync
+        // would otherwise leave an orphaned entry.
+        let fts = store.hybrid_search("alpha", 10, &opt).await.unwrap();
+        assert!(fts.iter().all(|d| d.page_content != "alpha"));
+    }
+
+    #[tokio::test]
+    async fn delete_by_filter_removes_matching_rows() {
+        let store = memory_store().await;
+        let opt = SqliteOptions::default();
+
+        store
+            .add_documents(
+                &[
+                    doc("keep", &[("lang", json!("rust"))]),
+                    doc("drop", &[("lang", json!("go"))]),
+                ],
+                &opt,
+            )
+            .await
+            .unwrap();
+
+        store
+            .delete_by_filter(&SqliteFilter::Eq("lang".into(), json!("go")))
+            .await
+            .unwrap();
+
+        let hits = store.similarity_search("keep", 10, &opt).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].page_content, "keep");
+    }
+
+    #[tokio::test]
+    async fn pre_delete_collection_starts_from_clean_state() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = StoreBuilder::new()
+            .pool(pool)
+            .table("documents")
+            .vector_dimensions(DIM)
+            .embedder(MockEmbedder)
+            .pre_delete_collection(true)
+            .build()
+            .await
+            .unwrap();
+
+        store.initialize().await.unwrap();
+        let opt = SqliteOptions::default();
+        store
+            .add_documents(&[doc("first", &[])], &opt)
+            .await
+            .unwrap();
+
+        // Re-initializing drops and recreates the collection, clearing rows.
+        store.initialize().await.unwrap();
+        let hits = store.similarity_search("first", 10, &opt).await.unwrap();
+        assert!(hits.is_empty());
+    }
+
+    #[tokio::test]
+    async fn hybrid_search_fuses_keyword_and_vector_hits() {
+        let store = memory_store().await;
+        let opt = SqliteOptions::default();
+
+        store
+            .add_documents(
+                &[
+                    doc("the quick brown fox", &[]),
+                    doc("a lazy dog sleeps", &[]),
+                    doc("unrelated content here", &[]),
+                ],
+                &opt,
+            )
+            .await
+            .unwrap();
+
+        // A query with FTS5-significant punctuation must not raise a syntax
+        // error, and the lexically matching document should surface.
+        let hits = store.hybrid_search("lazy-dog", 3, &opt).await.unwrap();
+        assert!(hits.iter().any(|d| d.page_content == "a lazy dog sleeps"));
+
+        // A blank query skips the keyword leg but still runs the vector leg.
+        let blank = store.hybrid_search("   ", 3, &opt).await.unwrap();
+        assert!(!blank.is_empty());
+    }
+
+    #[tokio::test]
+    async fn similarity_search_by_id_excludes_seed() {
+        let store = memory_store().await;
+        let opt = SqliteOptions::default();
+
+        let ids = store
+            .add_documents(&[doc("apple", &[]), doc("apple", &[]), doc("zebra", &[])], &opt)
+            .await
+            .unwrap();
+        let seed: i64 = ids[0].parse().unwrap();
+
+        let hits = store.similarity_search_by_id(seed, 10, &opt).await.unwrap();
+        // the seed itself is never returned...
+        assert_eq!(hits.len(), 2);
+        // ...and its identical twin ranks first by distance.
+        assert_eq!(hits[0].page_content, "apple");
+    }
+
+    #[tokio::test]
+    async fn similarity_search_by_vector_matches_nearest() {
+        let store = memory_store().await;
+        let opt = SqliteOptions::default();
+
+        store
+            .add_documents(&[doc("apple", &[]), doc("zebra", &[])], &opt)
+            .await
+            .unwrap();
+
+        let hits = store
+            .similarity_search_by_vector(&embed("apple"), 1, &opt)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].page_content, "apple");
+    }
+}